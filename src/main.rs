@@ -6,10 +6,17 @@
 use std::{
     collections::HashMap,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use anyhow::Context;
+use git2::{Cred, FetchOptions, RemoteCallbacks};
 use serde::Deserialize;
+use syntect::{
+    html::{ClassStyle, ClassedHTMLGenerator},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
 use walkdir::{DirEntry, WalkDir};
 
 #[derive(Deserialize)]
@@ -40,7 +47,29 @@ struct User {
 
 type LineLookup = HashMap<PathBuf, Vec<u32>>;
 
-fn main() -> anyhow::Result<()> {
+/// Per-file, per-PR hunk content, in diff order, so a reviewer can read the actual change
+/// without leaving the page.
+type HunkLookup = HashMap<PathBuf, Vec<DiffLineRecord>>;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DiffLineKind {
+    Addition,
+    Deletion,
+    Context,
+}
+
+struct DiffLineRecord {
+    kind: DiffLineKind,
+    content: String,
+}
+
+/// Upper bound on fetches running against the shared on-disk repo at once. libgit2 only
+/// guarantees concurrent writes to one `.git` directory are safe when they're bounded/serialized
+/// - unbounded parallel ref/pack writes are a known source of intermittent lock contention.
+const MAX_CONCURRENT_FETCHES: usize = 8;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
     let args: Vec<_> = std::env::args().collect();
 
     let repo_path = PathBuf::from(
@@ -51,6 +80,29 @@ fn main() -> anyhow::Result<()> {
         args.get(2)
             .context("Usage: cargo run <repo path> <json path>")?,
     );
+    // Real three-way merges are far more expensive than the line-overlap heuristic, so they're
+    // opt-in: `cargo run <repo path> <json path> --accurate`.
+    let accurate_conflicts = args[3..].iter().any(|arg| arg == "--accurate");
+
+    // How many lines apart two PRs' recorded ranges can be and still count as overlapping in the
+    // conflict matrix. Defaults to 0 (only literally shared line numbers count); configurable via
+    // `cargo run <repo path> <json path> --conflict-slop=<n>`.
+    let conflict_slop: u32 = args[3..]
+        .iter()
+        .find_map(|arg| arg.strip_prefix("--conflict-slop="))
+        .map(|value| {
+            value
+                .parse()
+                .context("--conflict-slop must be a non-negative integer")
+        })
+        .transpose()?
+        .unwrap_or(0);
+
+    // Similarity thresholds (0-100) for git's rename/copy detection when diffing a PR branch
+    // against its base, so a file moved or copied in a PR still tracks against its new path.
+    // Configurable via `--rename-threshold=<n>` / `--copy-threshold=<n>`.
+    let rename_similarity_threshold = parse_flag(&args[3..], "--rename-threshold=", 50)?;
+    let copy_similarity_threshold = parse_flag(&args[3..], "--copy-threshold=", 50)?;
 
     let repo = git2::Repository::open(&repo_path)?;
 
@@ -59,13 +111,13 @@ fn main() -> anyhow::Result<()> {
 
     println!("Fetching pull requests...");
 
+    let mut fetch_tasks = Vec::new();
+    let fetch_semaphore = Arc::new(tokio::sync::Semaphore::new(MAX_CONCURRENT_FETCHES));
+
     for pull_request in data.iter().take(100) {
-        let user = &pull_request.head_repository_owner.login;
-        let repo_name = &pull_request.head_repository.name;
-        let from_branch = &pull_request.head_ref_name;
         let to_branch = format!("pull-request-{}", pull_request.number);
 
-        if let Ok(pr_branch) = repo.find_branch(&to_branch, git2::BranchType::Local) {
+        let needs_fetch = if let Ok(pr_branch) = repo.find_branch(&to_branch, git2::BranchType::Local) {
             let pr_branch_oid = pr_branch
                 .get()
                 .peel_to_commit()
@@ -73,18 +125,42 @@ fn main() -> anyhow::Result<()> {
                 .id();
             let target_oid = git2::Oid::from_str(&pull_request.head_ref_oid)?;
 
-            // Only run get fetch if the local oid doesn't match the target oid from the json
-            if pr_branch_oid != target_oid {
-                fetch_pull_request_branch(&repo_path, user, repo_name, from_branch, &to_branch)?;
-            }
+            // Only run the fetch if the local oid doesn't match the target oid from the json
+            pr_branch_oid != target_oid
         } else {
-            fetch_pull_request_branch(&repo_path, user, repo_name, from_branch, &to_branch)?;
+            true
+        };
+
+        if needs_fetch {
+            let repo_path = repo_path.clone();
+            let user = pull_request.head_repository_owner.login.clone();
+            let repo_name = pull_request.head_repository.name.clone();
+            let from_branch = pull_request.head_ref_name.clone();
+
+            // Acquired before spawning (not inside the blocking closure) so at most
+            // MAX_CONCURRENT_FETCHES fetches ever run against the shared repo at once; the rest
+            // queue here instead of all hitting the ODB/refdb in parallel.
+            let permit = fetch_semaphore
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("fetch semaphore is never closed");
+
+            fetch_tasks.push(tokio::task::spawn_blocking(move || {
+                let _permit = permit;
+                fetch_pull_request_branch(&repo_path, &user, &repo_name, &from_branch, &to_branch)
+            }));
         }
     }
 
+    for task in fetch_tasks {
+        task.await.context("Fetch task panicked")??;
+    }
+
     println!("Calculating diffs...");
 
     let mut pr_lines_lookup = HashMap::<u32, LineLookup>::new();
+    let mut pr_hunks_lookup = HashMap::<u32, HunkLookup>::new();
 
     // Skip files like Cargo.lock where the conflicts are not meaningful
     let ignore_files = [PathBuf::from("Cargo.lock")];
@@ -101,70 +177,551 @@ fn main() -> anyhow::Result<()> {
         let common_ancestor_commit = repo.find_commit(common_ancester_oid)?;
         let common_ancester_tree = common_ancestor_commit.tree()?;
 
-        let diff =
-            repo.diff_tree_to_tree(Some(&common_ancester_tree), Some(&branch_head_tree), None)?;
+        let mut diff_options = git2::DiffOptions::new();
+        let mut diff = repo.diff_tree_to_tree(
+            Some(&common_ancester_tree),
+            Some(&branch_head_tree),
+            Some(&mut diff_options),
+        )?;
+
+        diff.find_similar(Some(
+            git2::DiffFindOptions::new()
+                .renames(true)
+                .copies(true)
+                .rename_threshold(rename_similarity_threshold)
+                .copy_threshold(copy_similarity_threshold),
+        ))?;
 
         let mut file_line_map = LineLookup::new();
+        let mut file_hunk_map = HunkLookup::new();
+        let mut rename_map = HashMap::<PathBuf, PathBuf>::new();
 
         diff.foreach(
             &mut |_, _| true,
             None,
             Some(&mut |diff_delta, diff_hunk| {
-                if let Some(path) = diff_delta.old_file().path() {
+                if let Some(new_path) = diff_delta.new_file().path() {
                     if diff_hunk.old_lines() != 0 {
-                        let path = path.to_path_buf();
+                        let new_path = new_path.to_path_buf();
 
-                        if ignore_files.contains(&path) {
+                        if ignore_files.contains(&new_path) {
                             return true;
                         }
 
+                        if let Some(old_path) = diff_delta.old_file().path() {
+                            if old_path != new_path {
+                                rename_map.insert(old_path.to_path_buf(), new_path.clone());
+                            }
+                        }
+
                         // Record all the lines from the old side of the diff which means we record context lines as
                         // well. ie. lines that haven't actually changed. This might be ok as it'll give us an idea
                         // when PRs impact code that is very close to each other but we might also want to try to
                         // improve it in the future
+                        //
+                        // Keyed by the delta's *new* path (post rename/copy detection) so a file
+                        // moved within the PR still lands on the path the working tree walker sees.
                         let start = diff_hunk.old_start();
                         let line_count = diff_hunk.old_lines();
                         let mut old_lines: Vec<u32> =
                             (start..(start + line_count)).into_iter().collect();
 
                         file_line_map
-                            .entry(path)
+                            .entry(new_path)
                             .and_modify(|lines| lines.append(&mut old_lines))
                             .or_insert_with(|| old_lines);
                     }
                 }
                 true
             }),
-            None,
+            Some(&mut |diff_delta, _diff_hunk, diff_line| {
+                let kind = match diff_line.origin() {
+                    '+' => DiffLineKind::Addition,
+                    '-' => DiffLineKind::Deletion,
+                    ' ' => DiffLineKind::Context,
+                    // File headers, "no newline" markers, binary notices, etc. - not a content line.
+                    _ => return true,
+                };
+
+                let Some(new_path) = diff_delta.new_file().path() else {
+                    return true;
+                };
+                let new_path = new_path.to_path_buf();
+
+                if ignore_files.contains(&new_path) {
+                    return true;
+                }
+
+                let content = String::from_utf8_lossy(diff_line.content())
+                    .trim_end_matches('\n')
+                    .to_string();
+
+                file_hunk_map
+                    .entry(new_path)
+                    .or_default()
+                    .push(DiffLineRecord { kind, content });
+
+                true
+            }),
         )?;
 
+        pr_hunks_lookup.insert(pr.number, file_hunk_map);
+
+        if !rename_map.is_empty() {
+            let renames = rename_map
+                .iter()
+                .map(|(old, new)| format!("{} -> {}", old.display(), new.display()))
+                .collect::<Vec<_>>()
+                .join(", ");
+            println!("PR #{}: followed rename/copy: {renames}", pr.number);
+        }
+
         pr_lines_lookup.insert(pr.number, file_line_map);
     }
 
-    let html = generate_html(&repo_path, &pr_lines_lookup)?;
+    println!("Rendering per-file source views...");
+
+    let syntax_set = SyntaxSet::load_defaults_newlines();
+
+    // Tracks which files actually got a detail page written, so the file list only links to
+    // pages that exist - generate_file_detail_html can bail out (binary/non-UTF-8 content).
+    let mut detail_pages_written = std::collections::HashSet::new();
+
+    for entry in walk_files(&repo_path) {
+        let line_counts = line_counts_for_file(&entry, &pr_lines_lookup);
+        if line_counts.is_empty() && !file_has_hunks(&entry, &pr_hunks_lookup) {
+            continue;
+        }
+
+        if let Some(html) = generate_file_detail_html(
+            &repo_path,
+            &entry,
+            &line_counts,
+            &pr_hunks_lookup,
+            &syntax_set,
+        )? {
+            std::fs::write(detail_file_name(&entry), html)?;
+            detail_pages_written.insert(entry);
+        }
+    }
+
+    let html = generate_html(&repo_path, &pr_lines_lookup, &detail_pages_written)?;
     std::fs::write("prmap.html", html)?;
 
+    println!("Computing PR conflict matrix...");
+
+    let conflict_matrix = conflict_matrix(&pr_lines_lookup, conflict_slop);
+
+    let real_conflicts = if accurate_conflicts {
+        println!("Computing real merge conflicts...");
+        detect_merge_conflicts(&repo, &data, &ignore_files)?
+    } else {
+        HashMap::new()
+    };
+
+    let conflict_html =
+        generate_conflict_matrix_html(&pr_lines_lookup, &conflict_matrix, &real_conflicts);
+    std::fs::write("conflicts.html", conflict_html)?;
+
     Ok(())
 }
 
-fn is_hidden(entry: &DirEntry) -> bool {
-    entry
-        .file_name()
-        .to_str()
-        .map(|s| s.starts_with("."))
-        .unwrap_or(false)
+/// Finds `<prefix><value>` among `args` and parses `<value>` as a percentage (0-100), falling
+/// back to `default` if the flag wasn't passed.
+fn parse_flag(args: &[String], prefix: &str, default: u16) -> anyhow::Result<u16> {
+    args.iter()
+        .find_map(|arg| arg.strip_prefix(prefix))
+        .map(|value| {
+            let parsed: u16 = value
+                .parse()
+                .with_context(|| format!("{prefix}<n> must be an integer between 0 and 100"))?;
+            anyhow::ensure!(
+                (0..=100).contains(&parsed),
+                "{prefix}<n> must be an integer between 0 and 100"
+            );
+            Ok(parsed)
+        })
+        .transpose()
+        .map(|parsed| parsed.unwrap_or(default))
 }
 
-fn generate_html(
-    repo_path: &Path,
+/// For every pair of PRs, actually merges their head trees (using `git2`'s merge machinery
+/// rather than the line-overlap heuristic) and records which paths would genuinely conflict,
+/// ignoring `ignore_files` (e.g. Cargo.lock) the same way the line-overlap heuristic does.
+///
+/// Only pairs with at least one conflicting path are present in the result.
+fn detect_merge_conflicts(
+    repo: &git2::Repository,
+    data: &[PullRequest],
+    ignore_files: &[PathBuf],
+) -> anyhow::Result<HashMap<(u32, u32), Vec<PathBuf>>> {
+    let mut prs: Vec<&PullRequest> = data.iter().take(100).collect();
+    prs.sort_by_key(|pr| pr.number);
+
+    let mut conflicts = HashMap::new();
+
+    for (i, pr_a) in prs.iter().enumerate() {
+        let oid_a = git2::Oid::from_str(&pr_a.head_ref_oid)?;
+        let commit_a = repo.find_commit(oid_a)?;
+
+        for pr_b in &prs[(i + 1)..] {
+            let oid_b = git2::Oid::from_str(&pr_b.head_ref_oid)?;
+            let commit_b = repo.find_commit(oid_b)?;
+
+            let ancestor_oid = repo.merge_base(oid_a, oid_b)?;
+            let ancestor_tree = repo.find_commit(ancestor_oid)?.tree()?;
+
+            let index =
+                repo.merge_trees(&ancestor_tree, &commit_a.tree()?, &commit_b.tree()?, None)?;
+
+            if !index.has_conflicts() {
+                continue;
+            }
+
+            let mut paths: Vec<PathBuf> = index
+                .conflicts()?
+                .filter_map(|conflict| conflict.ok())
+                .filter_map(|conflict| conflict.our.or(conflict.their).or(conflict.ancestor))
+                .filter_map(|entry| {
+                    std::str::from_utf8(&entry.path).ok().map(PathBuf::from)
+                })
+                .filter(|path| !ignore_files.contains(path))
+                .collect();
+            paths.sort_unstable();
+            paths.dedup();
+
+            if !paths.is_empty() {
+                conflicts.insert((pr_a.number, pr_b.number), paths);
+            }
+        }
+    }
+
+    Ok(conflicts)
+}
+
+/// For every pair of PRs, the number of recorded lines they touch in common (or within
+/// `slop` lines of each other), summed across every file they both modify.
+///
+/// Only the `(a, b)` entry with `a < b` is populated, and its score counts the lines of the
+/// lower-numbered PR `a` (see `overlap_count`). With `slop == 0` that equals the count from
+/// `b`'s side; with `slop > 0` the two counts can differ, and `b`'s side is not computed.
+fn conflict_matrix(
     pr_lines_lookup: &HashMap<u32, LineLookup>,
-) -> anyhow::Result<String> {
-    let walker = WalkDir::new(repo_path).sort_by_file_name().into_iter();
-    let walker = walker
-        .filter_entry(|e| !is_hidden(e))
+    slop: u32,
+) -> HashMap<(u32, u32), usize> {
+    let mut pr_numbers: Vec<u32> = pr_lines_lookup.keys().copied().collect();
+    pr_numbers.sort_unstable();
+
+    let mut matrix = HashMap::new();
+
+    for (i, &a) in pr_numbers.iter().enumerate() {
+        for &b in &pr_numbers[(i + 1)..] {
+            let lines_a = &pr_lines_lookup[&a];
+            let lines_b = &pr_lines_lookup[&b];
+
+            let score: usize = lines_a
+                .keys()
+                .filter_map(|path| lines_b.get(path).map(|other| (path, other)))
+                .map(|(path, other_lines)| overlap_count(&lines_a[path], other_lines, slop))
+                .sum();
+
+            if score > 0 {
+                matrix.insert((a, b), score);
+            }
+        }
+    }
+
+    matrix
+}
+
+/// Counts how many lines in `a` fall within `slop` of some line in `b`, treating both as sets
+/// (duplicate line numbers, e.g. from overlapping hunks, are only counted once).
+fn overlap_count(a: &[u32], b: &[u32], slop: u32) -> usize {
+    let mut a: Vec<u32> = a.to_vec();
+    let mut b: Vec<u32> = b.to_vec();
+    a.sort_unstable();
+    a.dedup();
+    b.sort_unstable();
+    b.dedup();
+
+    a.iter()
+        .filter(|&&line| {
+            b.iter()
+                .any(|&other| line.abs_diff(other) <= slop)
+        })
+        .count()
+}
+
+fn generate_conflict_matrix_html(
+    pr_lines_lookup: &HashMap<u32, LineLookup>,
+    conflict_matrix: &HashMap<(u32, u32), usize>,
+    real_conflicts: &HashMap<(u32, u32), Vec<PathBuf>>,
+) -> String {
+    let mut pr_numbers: Vec<u32> = pr_lines_lookup.keys().copied().collect();
+    pr_numbers.sort_unstable();
+
+    let max_score = conflict_matrix.values().copied().max().unwrap_or(1).max(1);
+    let gradient = colorgrad::spectral();
+    let (min, max) = gradient.domain();
+
+    let score_for = |a: u32, b: u32| -> usize {
+        if a == b {
+            0
+        } else {
+            let key = if a < b { (a, b) } else { (b, a) };
+            conflict_matrix.get(&key).copied().unwrap_or(0)
+        }
+    };
+
+    let real_conflict_for = |a: u32, b: u32| -> Option<&Vec<PathBuf>> {
+        let key = if a < b { (a, b) } else { (b, a) };
+        real_conflicts.get(&key)
+    };
+
+    let markup = maud::html! {
+        (maud::DOCTYPE)
+        html {
+            head {
+                meta charset="utf-8";
+                title { "PR conflict matrix" }
+                style {
+                    (maud::PreEscaped(r#"
+table { border-collapse: collapse; }
+th, td { width: 1.75rem; height: 1.75rem; text-align: center; font-size: 0.6rem; }
+th { font-weight: normal; }
+                    "#))
+                }
+            }
+            body {
+                h1 { "Public Relations" }
+                p { a href="prmap.html" { "Back to file list" } }
+                p { "Cell color is the line-overlap heuristic; a red border marks a pair with a genuine three-way merge conflict (requires running with --accurate)." }
+                table {
+                    tr {
+                        th { }
+                        @for b in &pr_numbers {
+                            th { (b.to_string()) }
+                        }
+                    }
+                    @for a in &pr_numbers {
+                        tr {
+                            th { (a.to_string()) }
+                            @for b in &pr_numbers {
+                                @let score = score_for(*a, *b);
+                                @let real = real_conflict_for(*a, *b);
+                                @let fraction: f64 = 1.0 - (score as f64 / max_score as f64);
+                                @let color = gradient.at(min + fraction * (max - min));
+                                @let style = format!(
+                                    "background-color: rgb({}, {}, {}); {}",
+                                    color.r * 255.0,
+                                    color.g * 255.0,
+                                    color.b * 255.0,
+                                    if real.is_some() { "border: 2px solid red;" } else { "" }
+                                );
+                                @let title = match real {
+                                    Some(paths) => format!(
+                                        "PR #{a} vs PR #{b}: {score} overlapping lines, real conflict in {}",
+                                        paths.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ")
+                                    ),
+                                    None => format!("PR #{a} vs PR #{b}: {score} overlapping lines"),
+                                };
+                                @if score > 0 || real.is_some() {
+                                    td style=(style) title=(title) { (score.to_string()) }
+                                } @else {
+                                    td { }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    };
+
+    markup.into_string()
+}
+
+/// Number of PRs recording each line of `entry`, keyed by 1-based line number.
+fn line_counts_for_file(entry: &Path, pr_lines_lookup: &HashMap<u32, LineLookup>) -> HashMap<u32, usize> {
+    let mut counts = HashMap::new();
+
+    for file_line_map in pr_lines_lookup.values() {
+        if let Some(lines) = file_line_map.get(entry) {
+            for line in lines {
+                *counts.entry(*line).or_insert(0) += 1;
+            }
+        }
+    }
+
+    counts
+}
+
+/// Whether any PR recorded hunk content for `entry`. Catches files with no recorded line counts
+/// (e.g. brand-new files added by a PR, where there's no "old" side to record context/removed
+/// lines from) that should still get a detail page so their hunks can render.
+fn file_has_hunks(entry: &Path, pr_hunks_lookup: &HashMap<u32, HunkLookup>) -> bool {
+    pr_hunks_lookup
+        .values()
+        .any(|file_hunk_map| file_hunk_map.contains_key(entry))
+}
+
+/// Turns a repo-relative path into a filesystem-safe name for its detail page.
+fn detail_file_name(entry: &Path) -> String {
+    let sanitized = entry
+        .display()
+        .to_string()
+        .replace(['/', '\\'], "_");
+    format!("file_{sanitized}.html")
+}
+
+/// Renders a single source file with each line tinted by how many PRs touch it.
+///
+/// Returns `None` if the file can't be read as UTF-8 text (binary files, deleted files, etc.).
+fn generate_file_detail_html(
+    repo_path: &Path,
+    entry: &Path,
+    line_counts: &HashMap<u32, usize>,
+    pr_hunks_lookup: &HashMap<u32, HunkLookup>,
+    syntax_set: &SyntaxSet,
+) -> anyhow::Result<Option<String>> {
+    let full_path = repo_path.join(entry);
+    let Ok(source) = std::fs::read_to_string(&full_path) else {
+        return Ok(None);
+    };
+
+    let syntax = entry
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(|ext| syntax_set.find_syntax_by_extension(ext))
+        .unwrap_or_else(|| syntax_set.find_syntax_plain_text());
+
+    let mut generator =
+        ClassedHTMLGenerator::new_with_class_style(syntax, syntax_set, ClassStyle::Spaced);
+    for line in LinesWithEndings::from(&source) {
+        generator.parse_html_for_line_which_includes_newline(line)?;
+    }
+
+    let max_count = line_counts.values().copied().max().unwrap_or(1).max(1);
+    let gradient = colorgrad::spectral();
+    let (min, max) = gradient.domain();
+
+    // ClassedHTMLGenerator emits one `<span class="line">`-free fragment for the whole
+    // file, so split back into per-line strings to pair each with its own heatmap swatch.
+    let highlighted = generator.finalize();
+    let mut rendered_lines = Vec::new();
+    for (index, line_html) in highlighted.lines().enumerate() {
+        let line_number = (index + 1) as u32;
+        let count = line_counts.get(&line_number).copied().unwrap_or(0);
+        let fraction: f64 = 1.0 - (count as f64 / max_count as f64);
+        let color = gradient.at(min + fraction * (max - min));
+        let style = format!(
+            "background-color: rgb({}, {}, {})",
+            color.r * 255.0,
+            color.g * 255.0,
+            color.b * 255.0
+        );
+        rendered_lines.push(format!(
+            r#"<div class="source-line" style="{style}"><span class="gutter">{line_number}</span><pre>{line_html}</pre></div>"#
+        ));
+    }
+
+    let body = maud::html! {
+        (maud::DOCTYPE)
+        html {
+            head {
+                meta charset="utf-8";
+                title { (entry.display().to_string()) }
+                style {
+                    (maud::PreEscaped(r#"
+.source-line { display: flex; }
+.source-line .gutter { width: 3rem; text-align: right; padding-right: 1rem; color: #888; user-select: none; }
+.source-line pre { margin: 0; flex: 1; white-space: pre-wrap; }
+.diff-add, .diff-del, .diff-context { display: flex; }
+.diff-add { background-color: #e6ffed; }
+.diff-del { background-color: #ffeef0; }
+.diff-marker { width: 1.5rem; text-align: center; user-select: none; color: #888; }
+.diff-content pre, .diff-marker + pre { margin: 0; }
+.diff-add pre, .diff-del pre, .diff-context pre { margin: 0; flex: 1; white-space: pre-wrap; }
+                    "#))
+                }
+            }
+            body {
+                h1 { (entry.display().to_string()) }
+                p { a href="prmap.html" { "Back to file list" } }
+                div {
+                    (maud::PreEscaped(rendered_lines.join("\n")))
+                }
+                (render_pr_hunks(entry, pr_hunks_lookup, syntax, syntax_set))
+            }
+        }
+    };
+
+    Ok(Some(body.into_string()))
+}
+
+/// Renders one red/green hunk block per PR that touches `entry`, so a reviewer can read the
+/// contended change without leaving the page.
+fn render_pr_hunks(
+    entry: &Path,
+    pr_hunks_lookup: &HashMap<u32, HunkLookup>,
+    syntax: &syntect::parsing::SyntaxReference,
+    syntax_set: &SyntaxSet,
+) -> maud::Markup {
+    let mut pr_numbers: Vec<u32> = pr_hunks_lookup
+        .iter()
+        .filter(|(_, hunks)| hunks.contains_key(entry))
+        .map(|(&number, _)| number)
+        .collect();
+    pr_numbers.sort_unstable();
+
+    maud::html! {
+        @if !pr_numbers.is_empty() {
+            h2 { "Hunks" }
+            @for pr_number in pr_numbers {
+                div {
+                    h3 { "PR #" (pr_number.to_string()) }
+                    div {
+                        @for line in &pr_hunks_lookup[&pr_number][entry] {
+                            @let (class, marker) = match line.kind {
+                                DiffLineKind::Addition => ("diff-add", "+"),
+                                DiffLineKind::Deletion => ("diff-del", "-"),
+                                DiffLineKind::Context => ("diff-context", " "),
+                            };
+                            div class=(class) {
+                                span class="diff-marker" { (marker) }
+                                pre class="diff-content" {
+                                    (maud::PreEscaped(highlight_single_line(&line.content, syntax, syntax_set)))
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Syntax-highlights a single line of diff content (added, removed or context) in isolation. Since each
+/// line is parsed without the surrounding file, multi-line constructs won't highlight perfectly,
+/// but it's enough to make a hunk readable at a glance.
+fn highlight_single_line(
+    content: &str,
+    syntax: &syntect::parsing::SyntaxReference,
+    syntax_set: &SyntaxSet,
+) -> String {
+    let mut generator =
+        ClassedHTMLGenerator::new_with_class_style(syntax, syntax_set, ClassStyle::Spaced);
+    let _ = generator.parse_html_for_line_which_includes_newline(&format!("{content}\n"));
+    generator.finalize()
+}
+
+fn walk_files(repo_path: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+    WalkDir::new(repo_path)
+        .sort_by_file_name()
         .into_iter()
+        .filter_entry(|e| !is_hidden(e))
         .filter_map(|entry| entry.ok())
-        .filter_map(|entry| {
+        .filter_map(move |entry| {
             if entry.file_type().is_dir() {
                 None
             } else {
@@ -174,7 +731,23 @@ fn generate_html(
                     .ok()
                     .map(|path| path.to_path_buf())
             }
-        });
+        })
+}
+
+fn is_hidden(entry: &DirEntry) -> bool {
+    entry
+        .file_name()
+        .to_str()
+        .map(|s| s.starts_with("."))
+        .unwrap_or(false)
+}
+
+fn generate_html(
+    repo_path: &Path,
+    pr_lines_lookup: &HashMap<u32, LineLookup>,
+    detail_pages_written: &std::collections::HashSet<PathBuf>,
+) -> anyhow::Result<String> {
+    let walker = walk_files(repo_path);
 
     let markup = maud::html! {
         (maud::DOCTYPE)
@@ -193,9 +766,10 @@ html {
             }
             body {
                 h1 { "Public Relations" }
+                p { a href="conflicts.html" { "PR conflict matrix" } }
                 ul {
                     @for entry in walker {
-                        (file_list_entry(&entry, pr_lines_lookup))
+                        (file_list_entry(&entry, pr_lines_lookup, detail_pages_written))
                     }
                 }
             }
@@ -205,7 +779,11 @@ html {
     Ok(markup.into_string())
 }
 
-fn file_list_entry(entry: &Path, pr_lines_lookup: &HashMap<u32, LineLookup>) -> maud::Markup {
+fn file_list_entry(
+    entry: &Path,
+    pr_lines_lookup: &HashMap<u32, LineLookup>,
+    detail_pages_written: &std::collections::HashSet<PathBuf>,
+) -> maud::Markup {
     let total_pr_count = pr_lines_lookup.len();
 
     let in_pr_count: u32 = pr_lines_lookup
@@ -230,30 +808,154 @@ fn file_list_entry(entry: &Path, pr_lines_lookup: &HashMap<u32, LineLookup>) ->
         li style=(li_style) {
             div style=(style) {
             }
-            (entry.display().to_string())
+            @if detail_pages_written.contains(entry) {
+                a href=(detail_file_name(entry)) { (entry.display().to_string()) }
+            } @else {
+                (entry.display().to_string())
+            }
         }
     }
 }
 
+/// Fetches `from_branch` of `user/repo_name` into the local `to_branch`, authenticating over
+/// SSH. Opens its own `Repository` handle so it can be run from a blocking task pool alongside
+/// fetches for other pull requests.
 fn fetch_pull_request_branch(
-    repo_path: &PathBuf,
+    repo_path: &Path,
     user: &str,
     repo_name: &str,
     from_branch: &str,
     to_branch: &str,
 ) -> anyhow::Result<()> {
-    let origin = format!("git@github.com:{user}/{repo_name}");
-    let from_to = format!("{from_branch}:{to_branch}");
+    let repo = git2::Repository::open(repo_path)?;
 
-    println!("Running git fetch {origin} {from_to}");
-    let output = std::process::Command::new("git")
-        .args(["fetch", &origin, &from_to])
-        .current_dir(repo_path)
-        .output()?;
+    let url = format!("git@github.com:{user}/{repo_name}");
+    let refspec = format!("{from_branch}:{to_branch}");
 
-    if !output.status.success() {
-        anyhow::bail!("Failed to run git fetch for {origin} {from_to}");
-    }
+    println!("Fetching {refspec} from {url}");
+
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(|_url, username_from_url, _allowed_types| {
+        let username = username_from_url.unwrap_or("git");
+
+        if let Ok(cred) = Cred::ssh_key_from_agent(username) {
+            return Ok(cred);
+        }
+
+        let home = std::env::var("HOME").unwrap_or_default();
+        for key_name in ["id_ed25519", "id_rsa"] {
+            let private_key = PathBuf::from(&home).join(".ssh").join(key_name);
+            if private_key.exists() {
+                return Cred::ssh_key(username, None, &private_key, None);
+            }
+        }
+
+        Err(git2::Error::from_str(
+            "no SSH credentials available: ssh-agent has no identities and no default key was found in ~/.ssh",
+        ))
+    });
+
+    let mut fetch_options = FetchOptions::new();
+    fetch_options.remote_callbacks(callbacks);
+
+    let mut remote = repo.remote_anonymous(&url)?;
+    remote
+        .fetch(&[refspec.as_str()], Some(&mut fetch_options), None)
+        .with_context(|| format!("Failed to fetch {refspec} from {url}"))?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_flag_falls_back_to_default_when_absent() {
+        let args = ["--accurate".to_string()];
+        assert_eq!(parse_flag(&args, "--rename-threshold=", 50).unwrap(), 50);
+    }
+
+    #[test]
+    fn parse_flag_parses_the_matching_prefix() {
+        let args = ["--rename-threshold=75".to_string()];
+        assert_eq!(parse_flag(&args, "--rename-threshold=", 50).unwrap(), 75);
+    }
+
+    #[test]
+    fn parse_flag_rejects_non_numeric_values() {
+        let args = ["--rename-threshold=not-a-number".to_string()];
+        assert!(parse_flag(&args, "--rename-threshold=", 50).is_err());
+    }
+
+    #[test]
+    fn parse_flag_rejects_values_outside_0_to_100() {
+        let args = ["--rename-threshold=1000".to_string()];
+        assert!(parse_flag(&args, "--rename-threshold=", 50).is_err());
+
+        let args = ["--rename-threshold=100".to_string()];
+        assert_eq!(parse_flag(&args, "--rename-threshold=", 50).unwrap(), 100);
+    }
+
+    #[test]
+    fn overlap_count_requires_exact_match_at_zero_slop() {
+        assert_eq!(overlap_count(&[10, 20], &[11, 21], 0), 0);
+        assert_eq!(overlap_count(&[10, 20], &[10, 21], 0), 1);
+    }
+
+    #[test]
+    fn overlap_count_includes_lines_within_slop() {
+        assert_eq!(overlap_count(&[10, 20], &[11, 21], 1), 2);
+        assert_eq!(overlap_count(&[10], &[12], 1), 0);
+        assert_eq!(overlap_count(&[10], &[12], 2), 1);
+    }
+
+    #[test]
+    fn overlap_count_dedupes_repeated_lines() {
+        assert_eq!(overlap_count(&[10, 10, 10], &[10], 0), 1);
+    }
+
+    #[test]
+    fn file_has_hunks_true_for_added_file_with_no_recorded_lines() {
+        // A PR that adds a brand-new file has nothing on the "old" side, so it never lands in
+        // line_counts_for_file, but its content still needs to render via render_pr_hunks.
+        let mut hunk_lookup = HashMap::new();
+        hunk_lookup.insert(
+            1,
+            HunkLookup::from([(
+                PathBuf::from("new.rs"),
+                vec![DiffLineRecord {
+                    kind: DiffLineKind::Addition,
+                    content: "fn main() {}".to_string(),
+                }],
+            )]),
+        );
+
+        assert!(file_has_hunks(Path::new("new.rs"), &hunk_lookup));
+        assert!(!file_has_hunks(Path::new("other.rs"), &hunk_lookup));
+    }
+
+    #[test]
+    fn conflict_matrix_only_populates_a_lt_b_for_overlapping_files() {
+        let mut lookup = HashMap::new();
+        lookup.insert(
+            1,
+            HashMap::from([(PathBuf::from("a.rs"), vec![10, 11])]),
+        );
+        lookup.insert(
+            2,
+            HashMap::from([(PathBuf::from("a.rs"), vec![11, 12])]),
+        );
+        lookup.insert(
+            3,
+            HashMap::from([(PathBuf::from("b.rs"), vec![1])]),
+        );
+
+        let matrix = conflict_matrix(&lookup, 0);
+
+        assert_eq!(matrix.get(&(1, 2)), Some(&1));
+        assert_eq!(matrix.get(&(2, 1)), None);
+        assert_eq!(matrix.get(&(1, 3)), None);
+        assert_eq!(matrix.get(&(2, 3)), None);
+    }
+}